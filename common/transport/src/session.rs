@@ -10,26 +10,555 @@ use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
 use log::info;
 use mbedtls::ssl::Context;
+#[cfg(feature = "initiator")]
+use mbedtls::ssl::Config;
+#[cfg(feature = "initiator")]
+use mbedtls::ssl::Session as TlsSession;
 use mbedtls_sys::psa::key_handle_t;
+#[cfg(feature = "responder")]
 use parsec_client::BasicClient;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{collections::HashMap, net::TcpStream, fmt::Debug, sync::{Mutex, atomic::{AtomicU32, Ordering}, Arc}};
+use std::{collections::HashMap, net::TcpStream, fmt::Debug, ops::{Deref, DerefMut}, sync::{Mutex, RwLock, atomic::{AtomicU32, Ordering}, Arc}};
 
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 ////////////////////////////////////////////////////////////////////////////////
 // Various bits of persistent state.
 ////////////////////////////////////////////////////////////////////////////////
 lazy_static! {
-    /// Hashmap of session IDs (handles) mapped to sessions
-    /// TODO: Remove expired sessions from hashmap
-    /// XXX: Do we really need a session ID?
-    static ref SESSIONS: Mutex<HashMap<SessionId, Session>> =
-        Mutex::new(HashMap::new());
-    static ref SESSION_COUNTER: AtomicU32 = AtomicU32::new(0);
+    /// The installed session store. Defaults to an in-process [`InMemorySessionStore`]
+    /// but can be swapped for a shared/persistent backend via [`set_session_store`].
+    static ref SESSION_STORE: RwLock<Arc<dyn SessionStore>> =
+        RwLock::new(Arc::new(InMemorySessionStore::default()));
 }
 
 pub type SessionId = u32;
 
+/// A pluggable backend for storing live [`Session`]s, so a deployment can back
+/// sessions with a shared/persistent store instead of per-process memory. The
+/// store owns ID allocation; the default is [`InMemorySessionStore`].
+///
+/// A [`Session`] holds a live TLS [`Context`] and cannot be serialized, so
+/// mutable access is handed back through a smart pointer (see
+/// [`get_mut`](SessionStore::get_mut)) that keeps whatever guard the backend
+/// needs alive for the duration of the borrow.
+pub trait SessionStore: Send + Sync {
+    /// Allocate a fresh, unused session ID.
+    fn generate_id(&self) -> SessionId;
+
+    /// Store `session` under `id`.
+    fn insert(&self, id: SessionId, session: Session) -> Result<()>;
+
+    /// Borrow the session stored under `id` for mutation, if it exists.
+    fn get_mut<'a>(&'a self, id: SessionId) -> Result<Option<Box<dyn DerefMut<Target = Session> + 'a>>>;
+
+    /// Remove and return the session stored under `id`, if it exists.
+    fn remove(&self, id: SessionId) -> Result<Option<Session>>;
+
+    /// Number of sessions currently stored.
+    fn len(&self) -> Result<usize>;
+
+    /// Snapshot of `(id, last_activity)` for every stored session, used by the
+    /// lifecycle layer to decide which sessions to expire or evict.
+    fn activity(&self) -> Result<Vec<(SessionId, SystemTime)>>;
+}
+
+/// Default, in-process [`SessionStore`] backed by a [`HashMap`] guarded by a
+/// [`Mutex`], with a monotonic counter for ID allocation.
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<SessionId, Session>>,
+    counter: AtomicU32,
+}
+
+impl Default for InMemorySessionStore {
+    fn default() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            counter: AtomicU32::new(0),
+        }
+    }
+}
+
+/// Mutable borrow of a single session that keeps the map lock held for as long
+/// as the caller holds the reference.
+struct InMemorySessionRef<'a> {
+    guard: std::sync::MutexGuard<'a, HashMap<SessionId, Session>>,
+    id: SessionId,
+}
+
+impl Deref for InMemorySessionRef<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        // Presence is checked before the ref is constructed.
+        self.guard.get(&self.id).expect("session removed while borrowed")
+    }
+}
+
+impl DerefMut for InMemorySessionRef<'_> {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.guard.get_mut(&self.id).expect("session removed while borrowed")
+    }
+}
+
+impl SessionStore for InMemorySessionStore {
+    fn generate_id(&self) -> SessionId {
+        self.counter.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn insert(&self, id: SessionId, session: Session) -> Result<()> {
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow!("Could not lock session hash table"))?
+            .insert(id, session);
+        Ok(())
+    }
+
+    fn get_mut<'a>(&'a self, id: SessionId) -> Result<Option<Box<dyn DerefMut<Target = Session> + 'a>>> {
+        let guard = self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow!("Could not lock session table"))?;
+        if !guard.contains_key(&id) {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(InMemorySessionRef { guard, id })))
+    }
+
+    fn remove(&self, id: SessionId) -> Result<Option<Session>> {
+        Ok(self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow!("Could not lock session table"))?
+            .remove(&id))
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow!("Could not lock session table"))?
+            .len())
+    }
+
+    fn activity(&self) -> Result<Vec<(SessionId, SystemTime)>> {
+        Ok(self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow!("Could not lock session table"))?
+            .iter()
+            .map(|(id, s)| (*id, s.last_activity))
+            .collect())
+    }
+}
+
+/// Install a custom [`SessionStore`], replacing the default in-memory one.
+///
+/// Fails if the currently installed store still holds live sessions: swapping
+/// it out from under them would drop those sessions (and their TLS contexts
+/// and PARSEC keys) without ever running [`Session::close`]. Call this once,
+/// before any session is created.
+pub fn set_session_store(store: Arc<dyn SessionStore>) -> Result<()> {
+    let mut current = SESSION_STORE
+        .write()
+        .map_err(|_| anyhow!("Could not lock session store"))?;
+    let live = current.len()?;
+    if live > 0 {
+        return Err(anyhow!(
+            "Cannot replace the session store while {} session(s) are still live; close them first",
+            live
+        ));
+    }
+    *current = store;
+    Ok(())
+}
+
+/// Return a handle to the currently installed [`SessionStore`].
+fn session_store() -> Result<Arc<dyn SessionStore>> {
+    Ok(SESSION_STORE
+        .read()
+        .map_err(|_| anyhow!("Could not lock session store"))?
+        .clone())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Attested-TLS session resumption.
+////////////////////////////////////////////////////////////////////////////////
+lazy_static! {
+    /// Cache of resumable attested-TLS material, keyed by responder URL.
+    #[cfg(feature = "initiator")]
+    static ref RESUMPTION: Mutex<ResumptionCache> = Mutex::new(ResumptionCache::default());
+}
+
+/// Policy governing attested-TLS session resumption. `ttl` bounds how long a
+/// cached session stays eligible for resumption before a fresh full handshake
+/// is forced so the peer's attestation evidence is re-verified; `max_entries`
+/// bounds the cache size (LRU eviction).
+#[cfg(feature = "initiator")]
+#[derive(Debug, Clone)]
+pub struct ResumptionPolicy {
+    ttl: Duration,
+    max_entries: usize,
+}
+
+#[cfg(feature = "initiator")]
+impl Default for ResumptionPolicy {
+    fn default() -> Self {
+        // Conservative defaults: five-minute freshness window, a handful of peers.
+        Self {
+            ttl: Duration::from_secs(300),
+            max_entries: 16,
+        }
+    }
+}
+
+/// A single resumable entry: the TLS config the peer was attested under, the
+/// negotiated mbedtls session (session ID/ticket plus master secret) that lets
+/// a later handshake skip straight to the abbreviated flow, the time the
+/// attestation evidence behind it was last verified, and the time it was last
+/// used (for LRU eviction).
+#[cfg(feature = "initiator")]
+struct ResumptionEntry {
+    config: Arc<Config>,
+    session: TlsSession,
+    verified_at: SystemTime,
+    last_used: SystemTime,
+}
+
+#[cfg(feature = "initiator")]
+struct ResumptionCache {
+    policy: ResumptionPolicy,
+    entries: HashMap<String, ResumptionEntry>,
+}
+
+#[cfg(feature = "initiator")]
+impl Default for ResumptionCache {
+    fn default() -> Self {
+        Self {
+            policy: ResumptionPolicy::default(),
+            entries: HashMap::new(),
+        }
+    }
+}
+
+/// Whether session material verified at `verified_at` is still inside `ttl` of
+/// `now`. Pulled out of [`ResumptionCache`] so the TTL arithmetic can be unit
+/// tested without needing a real TLS config or session.
+#[cfg(feature = "initiator")]
+fn is_within_ttl(verified_at: SystemTime, ttl: Duration, now: SystemTime) -> bool {
+    now.duration_since(verified_at).map(|age| age <= ttl).unwrap_or(false)
+}
+
+/// The key with the oldest `last_used` timestamp, i.e. the one LRU eviction
+/// should remove first. Pulled out of [`ResumptionCache`] for the same reason
+/// as [`is_within_ttl`].
+#[cfg(feature = "initiator")]
+fn least_recently_used<'a>(entries: impl Iterator<Item = (&'a String, SystemTime)>) -> Option<String> {
+    entries.min_by_key(|(_, last_used)| *last_used).map(|(k, _)| k.clone())
+}
+
+#[cfg(feature = "initiator")]
+impl ResumptionCache {
+    /// Look up a fresh, resumable session for `url`. Expired entries are
+    /// evicted so the next connection re-runs the full attested handshake.
+    fn lookup(&mut self, url: &str) -> Option<(Arc<Config>, TlsSession)> {
+        let now = SystemTime::now();
+        let fresh = match self.entries.get(url) {
+            Some(entry) => is_within_ttl(entry.verified_at, self.policy.ttl, now),
+            None => return None,
+        };
+        if !fresh {
+            self.entries.remove(url);
+            return None;
+        }
+        let entry = self.entries.get_mut(url)?;
+        entry.last_used = now;
+        Some((entry.config.clone(), entry.session.clone()))
+    }
+
+    /// Record a freshly negotiated session for `url`, evicting the
+    /// least-recently-used entry first if the cache is at capacity.
+    fn store(&mut self, url: &str, config: Arc<Config>, session: TlsSession) {
+        let now = SystemTime::now();
+        if !self.entries.contains_key(url) && self.entries.len() >= self.policy.max_entries {
+            if let Some(lru) =
+                least_recently_used(self.entries.iter().map(|(k, e)| (k, e.last_used)))
+            {
+                self.entries.remove(&lru);
+            }
+        }
+        self.entries.insert(
+            url.to_string(),
+            ResumptionEntry {
+                config,
+                session,
+                verified_at: now,
+                last_used: now,
+            },
+        );
+    }
+}
+
+#[cfg(all(test, feature = "initiator"))]
+mod resumption_cache_tests {
+    use super::*;
+
+    #[test]
+    fn ttl_window_closes_after_expiry() {
+        let now = SystemTime::now();
+        let verified_at = now - Duration::from_secs(10);
+        assert!(is_within_ttl(verified_at, Duration::from_secs(20), now));
+        assert!(!is_within_ttl(verified_at, Duration::from_secs(5), now));
+    }
+
+    #[test]
+    fn lru_pick_returns_oldest_last_used() {
+        let now = SystemTime::now();
+        let entries: HashMap<String, SystemTime> = [
+            ("a".to_string(), now - Duration::from_secs(30)),
+            ("b".to_string(), now - Duration::from_secs(5)),
+            ("c".to_string(), now - Duration::from_secs(60)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            least_recently_used(entries.iter().map(|(k, v)| (k, *v))),
+            Some("c".to_string())
+        );
+    }
+
+    #[test]
+    fn lru_pick_on_empty_set_is_none() {
+        let entries: HashMap<String, SystemTime> = HashMap::new();
+        assert_eq!(least_recently_used(entries.iter().map(|(k, v)| (k, *v))), None);
+    }
+}
+
+/// Tune the attested-TLS resumption policy: `ttl` is the freshness window after
+/// which a peer must be fully re-attested, and `max_entries` caps the number of
+/// cached peers (LRU eviction beyond that). Existing entries beyond the new cap
+/// are trimmed immediately.
+#[cfg(feature = "initiator")]
+pub fn set_resumption_policy(ttl: Duration, max_entries: usize) -> Result<()> {
+    let mut cache = RESUMPTION
+        .lock()
+        .map_err(|_| anyhow!("Could not lock resumption cache"))?;
+    cache.policy = ResumptionPolicy { ttl, max_entries };
+    while cache.entries.len() > max_entries {
+        let lru = least_recently_used(cache.entries.iter().map(|(k, e)| (k, e.last_used)));
+        match lru {
+            Some(k) => {
+                cache.entries.remove(&k);
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Session lifecycle: idle expiry and capacity-bounded eviction.
+////////////////////////////////////////////////////////////////////////////////
+lazy_static! {
+    /// The installed lifecycle policy. Disabled by default so that sessions
+    /// live until explicitly closed; call [`set_session_lifecycle`] to enable
+    /// idle expiry and/or a capacity cap.
+    static ref LIFECYCLE: RwLock<LifecyclePolicy> = RwLock::new(LifecyclePolicy::default());
+}
+
+/// Policy bounding how long sessions live and how many may be stored at once,
+/// so a long-running DPU process doesn't accumulate stale sessions (and their
+/// TLS contexts and PARSEC keys) without bound. `idle_ttl` closes sessions
+/// that have seen no traffic for too long, and `max_sessions` caps the store,
+/// evicting the least-recently-used session when a new one would exceed the
+/// cap.
+#[derive(Debug, Clone, Default)]
+struct LifecyclePolicy {
+    /// Close sessions idle for longer than this. `None` disables idle expiry.
+    idle_ttl: Option<Duration>,
+    /// Maximum number of concurrent sessions. `None` disables the cap.
+    max_sessions: Option<usize>,
+}
+
+/// IDs of sessions in `activity` that have been idle longer than `ttl`. Pulled
+/// out of [`Session::reap`] so the timestamp arithmetic can be unit tested
+/// without a real [`SessionStore`] or TLS context.
+fn idle_expired_ids(activity: &[(SessionId, SystemTime)], ttl: Duration, now: SystemTime) -> Vec<SessionId> {
+    activity
+        .iter()
+        // `SystemTime` is not guaranteed monotonic, so `last_activity` can land
+        // fractionally after `now` across threads/cores; treat that as "not
+        // idle" rather than tearing down an actively-used session.
+        .filter(|(_, last_activity)| now.duration_since(*last_activity).map(|idle| idle > ttl).unwrap_or(false))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// IDs to evict, oldest-`last_activity`-first, so that `activity.len() -
+/// evicted.len() <= cap`. Pulled out of [`Session::reap`] for the same reason
+/// as [`idle_expired_ids`].
+fn lru_evict_ids(activity: &[(SessionId, SystemTime)], cap: usize) -> Vec<SessionId> {
+    if activity.len() <= cap {
+        return Vec::new();
+    }
+    let mut by_age: Vec<(SessionId, SystemTime)> = activity.to_vec();
+    by_age.sort_by_key(|(_, last_activity)| *last_activity);
+    by_age
+        .into_iter()
+        .take(activity.len() - cap)
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Configure the session lifecycle: `idle_ttl` closes sessions that have seen no
+/// application traffic for that long, and `max_sessions` caps the number of live
+/// sessions (least-recently-used sessions are evicted first). Both bounds are
+/// enforced by a lazy sweep on every new session and can also be triggered
+/// manually via [`Session::reap_expired`].
+pub fn set_session_lifecycle(idle_ttl: Duration, max_sessions: usize) -> Result<()> {
+    *LIFECYCLE
+        .write()
+        .map_err(|_| anyhow!("Could not lock lifecycle policy"))? = LifecyclePolicy {
+        idle_ttl: Some(idle_ttl),
+        max_sessions: Some(max_sessions),
+    };
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Non-blocking I/O mode and event-loop registry.
+////////////////////////////////////////////////////////////////////////////////
+
+/// Whether a session's socket is driven synchronously or from an event loop.
+///
+/// In [`IoMode::Blocking`] (the default) `send_message`/`receive_message` block
+/// until the operation completes, as they always have. In
+/// [`IoMode::NonBlocking`] the underlying [`TcpStream`] is switched to
+/// non-blocking mode and those calls return a [`WouldBlock`] indication rather
+/// than blocking, so a single DPU process can multiplex many sessions off one
+/// thread instead of dedicating one per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoMode {
+    Blocking,
+    NonBlocking,
+}
+
+/// Marker error returned by `send_message`/`receive_message` when a session is
+/// in [`IoMode::NonBlocking`] and the operation cannot make progress without
+/// blocking. Callers should retry once the session signals readiness via
+/// [`Session::wants_read`]/[`Session::wants_write`].
+#[derive(Debug, Clone, Copy)]
+pub struct WouldBlock;
+
+impl std::fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "operation would block")
+    }
+}
+
+impl std::error::Error for WouldBlock {}
+
+/// True if `err` is, or was caused by, a would-block condition — either the
+/// [`WouldBlock`] marker or an underlying [`std::io::ErrorKind::WouldBlock`].
+///
+/// `tls::send_message`/`tcp::send_message`/`tls::receive_message` report I/O
+/// failures as `anyhow::Error`, and this file's own convention elsewhere is
+/// `.map_err(|e| anyhow!("...: {}", e))`, which discards the original
+/// `io::Error` as a source — if those helpers follow the same convention, the
+/// chain-based check below would never see the underlying `WouldBlock` kind.
+/// Fall back to matching the rendered message so a lossily-wrapped
+/// `WouldBlock` is still detected rather than surfacing as a hard error to a
+/// non-blocking caller. Only the innermost segment (after the last `": "`,
+/// which is where `anyhow!("...: {}", e)` appends the cause) is checked, and
+/// only for an exact match against `io::ErrorKind::WouldBlock`'s canonical
+/// text — a loose substring search anywhere in the message would also catch
+/// unrelated permanent errors that merely mention "would block" in prose.
+pub fn is_would_block(err: &anyhow::Error) -> bool {
+    let in_chain = err.chain().any(|cause| {
+        cause.is::<WouldBlock>()
+            || cause
+                .downcast_ref::<std::io::Error>()
+                .map(|io| io.kind() == std::io::ErrorKind::WouldBlock)
+                .unwrap_or(false)
+    });
+    in_chain
+        || err
+            .to_string()
+            .rsplit(": ")
+            .next()
+            .map(|tail| tail.eq_ignore_ascii_case("would block"))
+            .unwrap_or(false)
+}
+
+/// Readiness interest for a single session, as reported to an event loop.
+#[derive(Debug, Clone, Copy)]
+pub struct Interest {
+    pub session_id: SessionId,
+    /// The session wants to be polled for readability.
+    pub read: bool,
+    /// The session has buffered output and wants to be polled for writability.
+    pub write: bool,
+}
+
+/// Set of sessions a single event loop is multiplexing. The caller registers
+/// each non-blocking [`SessionId`], asks [`interests`](SessionRegistry::interests)
+/// for the read/write readiness to hand to its poller, and on a readiness
+/// event drives the session with [`Session::process_io`] followed by the usual
+/// `send_message`/`receive_message` calls.
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    ids: Vec<SessionId>,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking `session_id`. Ignored if already registered.
+    pub fn register(&mut self, session_id: SessionId) {
+        if !self.ids.contains(&session_id) {
+            self.ids.push(session_id);
+        }
+    }
+
+    /// Stop tracking `session_id`.
+    pub fn deregister(&mut self, session_id: SessionId) {
+        self.ids.retain(|id| *id != session_id);
+    }
+
+    /// The currently registered session IDs.
+    pub fn sessions(&self) -> &[SessionId] {
+        &self.ids
+    }
+
+    /// Current read/write interest for every registered session. Sessions that
+    /// have since been closed are skipped.
+    pub fn interests(&self) -> Result<Vec<Interest>> {
+        let mut interests = Vec::with_capacity(self.ids.len());
+        for &session_id in &self.ids {
+            match (Session::wants_read(session_id), Session::wants_write(session_id)) {
+                (Ok(read), Ok(write)) => interests.push(Interest {
+                    session_id,
+                    read,
+                    write,
+                }),
+                // A closed session no longer has any interest.
+                _ => continue,
+            }
+        }
+        Ok(interests)
+    }
+
+    /// Drive pending I/O for every registered session.
+    pub fn process_all(&self) -> Result<()> {
+        for &session_id in &self.ids {
+            // A session may have been closed out from under the loop.
+            let _ = Session::process_io(session_id);
+        }
+        Ok(())
+    }
+}
+
 #[cfg(feature = "responder")]
 #[allow(dead_code)]
 pub struct ResponderContext {
@@ -50,6 +579,17 @@ pub struct Session {
     /// Encryption mode. For performance reasons local connections (e.g. between host and DPU) should be unencrypted while remote connections (e.g. between two DPUs) should be encrypted.
     /// Defaults to TLS
     encryption_mode: EncryptionMode,
+    /// When the session was created.
+    created: SystemTime,
+    /// When the session last sent or received application data. Used by the
+    /// lifecycle layer to expire idle sessions.
+    last_activity: SystemTime,
+    /// Whether I/O blocks or is driven from an event loop. Defaults to
+    /// [`IoMode::Blocking`].
+    io_mode: IoMode,
+    /// In non-blocking mode, set when a write could not be fully flushed so the
+    /// session still wants to be polled for writability.
+    write_pending: bool,
     /// Additional context for miscellaneous responder-side data that must live through the entire session
     #[cfg(feature = "responder")]
     #[allow(dead_code)]
@@ -66,6 +606,47 @@ impl Session {
     pub fn from_url(responder_url: &str) -> Result<SessionId> {
         // TODO: Return session ID if session already exists
 
+        // If we attested this peer recently enough, hand mbedtls its saved
+        // session before establishing: on an abbreviated (resumed) handshake
+        // the peer does not resend its certificate, so the cert-verify
+        // callback that checks attestation evidence is not invoked again. If
+        // the peer declines to resume (e.g. its ticket expired independently)
+        // `establish` below just falls back to a full handshake and evidence
+        // is re-verified as normal, so this is never unsafe, only a speedup.
+        let resumed = RESUMPTION
+            .lock()
+            .map_err(|_| anyhow!("Could not lock resumption cache"))?
+            .lookup(responder_url);
+        if let Some((config, session)) = resumed {
+            info!("Attempting attested-TLS resumption with {}.", responder_url);
+            let socket = TcpStream::connect(responder_url)
+                .map_err(|e| anyhow!("Could not connect to responder on {}: {}", responder_url, e))?;
+            let mut tls_context = Context::new(config.clone());
+            tls_context
+                .set_session(&session)
+                .map_err(|e| anyhow!("Could not set cached TLS session: {}", e))?;
+            tls_context.establish(socket, None)?;
+            info!("TLS handshake resumed; attestation evidence not re-verified");
+
+            // Tickets rotate on use, so refresh the cached session material
+            // (and freshness window) from whatever was just negotiated.
+            if let Some(new_session) = tls_context.session() {
+                RESUMPTION
+                    .lock()
+                    .map_err(|_| anyhow!("Could not lock resumption cache"))?
+                    .store(responder_url, config, new_session.clone());
+            }
+
+            // Resumed sessions get a fresh ID so the public API is unchanged.
+            let session_id = Self::register(
+                tls_context,
+                #[cfg(feature = "responder")]
+                None,
+            )?;
+            info!("Resumed session added to store");
+            return Ok(session_id);
+        }
+
         // Connect to responder
 
         let mut time = SystemTime::now();
@@ -83,10 +664,10 @@ impl Session {
         info!("Establishing TLS server context...");
         let mut time = SystemTime::now();
         let mut time_refined = SystemTime::now();
-        let config = tls_server::generate_tls_server_config()?;
+        let config = Arc::new(tls_server::generate_tls_server_config()?);
         println!("---==== {}: time to TLS server - generate_tls_server_config  ({:?}):", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros(),  SystemTime::now().duration_since(time_refined).unwrap());
         let mut time_refined = SystemTime::now();
-        let mut tls_context = Context::new(Arc::new(config));
+        let mut tls_context = Context::new(config.clone());
         println!("---==== {}: time to TLS server - Context::new ({:?}):", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros(),  SystemTime::now().duration_since(time_refined).unwrap());
         let mut time_refined = SystemTime::now();
         tls_context.establish(socket, None)?;
@@ -94,21 +675,34 @@ impl Session {
         info!("TLS server context established");
         println!("---+++ {}: time to TLS server ({:?}):", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros(),  SystemTime::now().duration_since(time).unwrap());
 
-        // Add session to hashmap
-        let session_id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst);
-        SESSIONS
-            .lock()
-            .map_err(|_| anyhow!("Could not lock session hash table"))?
-            .insert(
-                session_id,
-                Self {
-                    tls_context,
-                    encryption_mode: EncryptionMode::Tls,
-                    responder_context: None,
-                }
-            );
-        info!("Session added to hashmap");
+        // Cache the negotiated session so subsequent connections to this peer
+        // can resume within the freshness window instead of re-attesting.
+        if let Some(session) = tls_context.session() {
+            RESUMPTION
+                .lock()
+                .map_err(|_| anyhow!("Could not lock resumption cache"))?
+                .store(responder_url, config, session.clone());
+        }
+
+        // Add session to the store
+        let session_id = Self::register(
+            tls_context,
+            #[cfg(feature = "responder")]
+            None,
+        )?;
+        info!("Session added to store");
+
+        Ok(session_id)
+    }
 
+    /// Like [`from_url`](Session::from_url), but leaves the session in
+    /// [`IoMode::NonBlocking`] so it can be driven from an event loop. The
+    /// attested-TLS handshake itself still completes synchronously; only the
+    /// subsequent application I/O is non-blocking.
+    #[cfg(feature = "initiator")]
+    pub fn from_url_nonblocking(responder_url: &str) -> Result<SessionId> {
+        let session_id = Self::from_url(responder_url)?;
+        Self::set_io_mode(session_id, IoMode::NonBlocking)?;
         Ok(session_id)
     }
 
@@ -119,6 +713,33 @@ impl Session {
     pub fn from_socket(socket: TcpStream) -> Result<SessionId> {
         // TODO: Return session ID if session already exists
 
+        // `generate_tls_client_config` creates its key under the same fixed
+        // name every time (`parsec-se-driver-key48879`), not a per-session
+        // one, so `Session::close`'s key_handle-based cleanup alone isn't
+        // enough: a session that never closes cleanly (crash, forced kill,
+        // dropped connection) leaves that name occupied and locks out every
+        // future `from_socket` with `PSA_ERROR_ALREADY_EXISTS`. Best-effort
+        // clear it before creating a fresh key, same as the old connect-time
+        // workaround this replaced. This does not by itself make concurrent
+        // `from_socket` calls safe, since the name is still shared rather
+        // than per-session; that needs `generate_tls_client_config` itself
+        // to mint a unique name.
+        // TODO: grab the PARSEC client instance (PARSEC_BASIC_CLIENT) already generated by the PARSEC SE driver
+        // TODO: find a better way to get the key's name
+        //
+        // The whole thing, not just the destroy call, is best-effort: a
+        // transient hiccup talking to the PARSEC service shouldn't hard-fail
+        // a new responder connection before it's even attempted a handshake.
+        if let Err(e) = (|| -> Result<()> {
+            let mut client = BasicClient::new_naked()?;
+            client.set_default_auth(Some(String::from("Parsec SE Driver")))?;
+            client.set_default_provider()?;
+            let _ = client.psa_destroy_key("parsec-se-driver-key48879");
+            Ok(())
+        })() {
+            info!("Best-effort stale PARSEC key cleanup skipped: {}", e);
+        }
+
         // Establish TLS client context
         info!("Establishing TLS client context...");
         let (config, key_handle, client_attestation_type_list) = tls_client::generate_tls_client_config()?;
@@ -126,58 +747,132 @@ impl Session {
         tls_context.establish(socket, None)?;
         info!("TLS client context established");
 
-        // Remove PARSEC key to avoid `PSA_ERROR_ALREADY_EXISTS` error next time we establish a TLS context using the same PARSEC service
-        // TODO: grab the PARSEC client instance (PARSEC_BASIC_CLIENT) already generated by the PARSEC SE driver
-        // TODO: find a better way to get the key's name
-        // TODO: also destroy key when destroying session
-        let mut client = BasicClient::new_naked()?;
-        client.set_default_auth(Some(String::from("Parsec SE Driver")))?;
-        client.set_default_provider()?;
-        let _ = client.psa_destroy_key("parsec-se-driver-key48879");
-
-        // Add session to hashmap
-        let session_id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst);
-        SESSIONS
-            .lock()
-            .map_err(|_| anyhow!("Could not lock session hash table"))?
-            .insert(
-                session_id,
-                Self {
-                    tls_context,
-                    encryption_mode: EncryptionMode::Tls,
-                    responder_context: Some(ResponderContext {
-                        key_handle: *key_handle,
-                        client_attestation_type_list: *client_attestation_type_list,
-                    }),
-                }
-            );
+        // Add session to the store
+        let session_id = Self::register(
+            tls_context,
+            Some(ResponderContext {
+                key_handle: *key_handle,
+                client_attestation_type_list: *client_attestation_type_list,
+            }),
+        )?;
 
         Ok(session_id)
     }
 
+    /// Like [`from_socket`](Session::from_socket), but leaves the session in
+    /// [`IoMode::NonBlocking`] so it can be driven from an event loop. The
+    /// attested-TLS handshake itself still completes synchronously; only the
+    /// subsequent application I/O is non-blocking.
+    #[cfg(feature = "responder")]
+    pub fn from_socket_nonblocking(socket: TcpStream) -> Result<SessionId> {
+        let session_id = Self::from_socket(socket)?;
+        Self::set_io_mode(session_id, IoMode::NonBlocking)?;
+        Ok(session_id)
+    }
+
     pub fn set_encryption_mode(session_id: SessionId, encryption_mode: EncryptionMode) -> Result<()> {
-        let mut s = SESSIONS
-            .lock()
-            .map_err(|_| anyhow!("Could not lock session table"))?;
-        let s = s
-            .get_mut(&session_id)
+        let store = session_store()?;
+        let mut s = store
+            .get_mut(session_id)?
             .ok_or(anyhow!("Session does not exist"))?;
         s.encryption_mode = encryption_mode;
         Ok(())
     }
 
+    /// Switch a session between blocking and event-loop-driven I/O. Flipping to
+    /// [`IoMode::NonBlocking`] puts the underlying socket into non-blocking mode
+    /// so that `send_message`/`receive_message` surface [`WouldBlock`] instead of
+    /// blocking; flipping back clears any pending-write state.
+    pub fn set_io_mode(session_id: SessionId, io_mode: IoMode) -> Result<()> {
+        let store = session_store()?;
+        let mut s = store
+            .get_mut(session_id)?
+            .ok_or(anyhow!("Session does not exist"))?;
+        s
+            .tls_context
+            .io_mut()
+            .ok_or(anyhow!("Context has no valid I/O"))?
+            .set_nonblocking(io_mode == IoMode::NonBlocking)
+            .map_err(|e| anyhow!("Could not set socket I/O mode: {}", e))?;
+        s.io_mode = io_mode;
+        if io_mode == IoMode::Blocking {
+            s.write_pending = false;
+        }
+        Ok(())
+    }
+
+    /// Whether a non-blocking session wants to be polled for readability. Always
+    /// true while non-blocking (the session is ready to receive whenever the peer
+    /// sends); always false in blocking mode, where readiness is not consulted.
+    pub fn wants_read(session_id: SessionId) -> Result<bool> {
+        let store = session_store()?;
+        let s = store
+            .get_mut(session_id)?
+            .ok_or(anyhow!("Session does not exist"))?;
+        Ok(s.io_mode == IoMode::NonBlocking)
+    }
+
+    /// Whether a non-blocking session has output that still needs flushing and so
+    /// wants to be polled for writability.
+    pub fn wants_write(session_id: SessionId) -> Result<bool> {
+        let store = session_store()?;
+        let s = store
+            .get_mut(session_id)?
+            .ok_or(anyhow!("Session does not exist"))?;
+        Ok(s.io_mode == IoMode::NonBlocking && s.write_pending)
+    }
+
+    /// Drive pending I/O on a non-blocking session in response to a readiness
+    /// event. `mbedtls_ssl_write` always tries to flush any already-queued
+    /// output before touching new data, so a zero-length write against
+    /// `tls_context` (not the raw socket, which has nothing to do with TLS
+    /// record framing) is the documented way to resume a stalled write
+    /// without re-supplying the original plaintext buffer. A would-block
+    /// result is not an error — it just means the session still wants to be
+    /// polled for writability. A no-op for blocking sessions or sessions with
+    /// nothing pending.
+    pub fn process_io(session_id: SessionId) -> Result<()> {
+        use std::io::Write;
+
+        let store = session_store()?;
+        let mut s = store
+            .get_mut(session_id)?
+            .ok_or(anyhow!("Session does not exist"))?;
+        if s.io_mode != IoMode::NonBlocking || !s.write_pending {
+            return Ok(());
+        }
+        match s.tls_context.write(&[]) {
+            Ok(_) => {
+                s.write_pending = false;
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(anyhow!("Could not flush pending session write: {}", e)),
+        }
+    }
+
+    /// Close and destroy a session. Removes it from the store, sends a TLS
+    /// close-notify shutdown to the peer, and on the responder side destroys the
+    /// associated PARSEC key so no `key_handle_t` material is leaked.
+    pub fn close(session_id: SessionId) -> Result<()> {
+        let store = session_store()?;
+        let session = store
+            .remove(session_id)?
+            .ok_or(anyhow!("Session does not exist"))?;
+        session.teardown();
+        Ok(())
+    }
+
     /// Send application message
     pub fn send_message<T>(session_id: SessionId, data: T) -> Result<()>
     where
     T: Serialize + Debug,
     {
-        let mut s = SESSIONS
-            .lock()
-            .map_err(|_| anyhow!("Could not lock session table"))?;
-        let s = s
-            .get_mut(&session_id)
+        let store = session_store()?;
+        let mut s = store
+            .get_mut(session_id)?
             .ok_or(anyhow!("Session does not exist"))?;
-        match s.encryption_mode {
+        let result = match s.encryption_mode {
             EncryptionMode::Tls => tls::send_message(&mut s.tls_context, data),
             EncryptionMode::Plaintext => tcp::send_message(
                 s
@@ -186,6 +881,22 @@ impl Session {
                     .ok_or(anyhow!("Context has no valid I/O"))?,
                 data
             ),
+        };
+        match result {
+            Ok(()) => {
+                // Only real traffic counts as activity: a non-blocking poll
+                // loop calls this repeatedly while waiting for readiness, and
+                // stamping on every such call would defeat idle-expiry for a
+                // session that's polled often but never actually sends.
+                s.last_activity = SystemTime::now();
+                s.write_pending = false;
+                Ok(())
+            }
+            Err(e) if s.io_mode == IoMode::NonBlocking && is_would_block(&e) => {
+                s.write_pending = true;
+                Err(WouldBlock.into())
+            }
+            Err(e) => Err(e),
         }
     }
 
@@ -194,13 +905,11 @@ impl Session {
     where
     T: DeserializeOwned + Debug,
     {
-        let mut s = SESSIONS
-            .lock()
-            .map_err(|_| anyhow!("Could not lock session table"))?;
-        let s = s
-            .get_mut(&session_id)
+        let store = session_store()?;
+        let mut s = store
+            .get_mut(session_id)?
             .ok_or(anyhow!("Session does not exist"))?;
-        match s.encryption_mode {
+        let result = match s.encryption_mode {
             EncryptionMode::Tls => tls::receive_message(&mut s.tls_context),
             EncryptionMode::Plaintext => tcp::receive_message(
                 s
@@ -208,6 +917,201 @@ impl Session {
                     .io_mut()
                     .ok_or(anyhow!("Context has no valid I/O"))?
             ),
+        };
+        match result {
+            Ok(data) => {
+                // See the matching comment in `send_message`: only stamp
+                // activity when something was actually received, not on
+                // every poll that comes back empty.
+                s.last_activity = SystemTime::now();
+                Ok(data)
+            }
+            Err(e) if s.io_mode == IoMode::NonBlocking && is_would_block(&e) => {
+                Err(WouldBlock.into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Register a freshly established session in the store, stamping it with
+    /// creation and last-activity timestamps and running a lazy sweep so idle
+    /// and over-cap sessions are reaped before the new one is returned.
+    fn register(tls_context: Context<TcpStream>, #[cfg(feature = "responder")] responder_context: Option<ResponderContext>) -> Result<SessionId> {
+        // Hold the store lock across generate_id/insert/reap (not just a
+        // cloned Arc) so a concurrent `set_session_store` can't swap the
+        // installed store mid-registration: an RwLock writer blocks until
+        // every reader — including this one — has finished, so the session
+        // can never end up inserted into a store that's already been
+        // replaced and is no longer reachable via `session_store`.
+        let guard = SESSION_STORE
+            .read()
+            .map_err(|_| anyhow!("Could not lock session store"))?;
+        let store: &Arc<dyn SessionStore> = &guard;
+        let now = SystemTime::now();
+        let session_id = store.generate_id();
+        store.insert(
+            session_id,
+            Self {
+                tls_context,
+                encryption_mode: EncryptionMode::Tls,
+                created: now,
+                last_activity: now,
+                io_mode: IoMode::Blocking,
+                write_pending: false,
+                #[cfg(feature = "responder")]
+                responder_context,
+            },
+        )?;
+        Self::reap(store)?;
+        Ok(session_id)
+    }
+
+    /// Time the session was created.
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    /// Time of the session's most recent application traffic.
+    pub fn last_activity(&self) -> SystemTime {
+        self.last_activity
+    }
+
+    /// Tear down a session's resources: send a TLS close-notify to the peer and,
+    /// on the responder side, destroy the PARSEC key so we don't leak
+    /// `key_handle_t` material.
+    fn teardown(mut self) {
+        // Best-effort TLS close-notify shutdown; the peer may already be gone.
+        let _ = self.tls_context.close();
+        #[cfg(feature = "responder")]
+        if let Some(responder_context) = self.responder_context.take() {
+            // SAFETY: `key_handle` was handed to us by the PARSEC SE driver for
+            // this session and is destroyed exactly once, here at teardown.
+            unsafe {
+                let _ = mbedtls_sys::psa::psa_destroy_key(responder_context.key_handle);
+            }
+        }
+    }
+
+    /// Lazy sweep: close and remove sessions idle past the configured TTL, then
+    /// evict least-recently-used sessions until the store is within its cap.
+    fn reap(store: &Arc<dyn SessionStore>) -> Result<()> {
+        let policy = LIFECYCLE
+            .read()
+            .map_err(|_| anyhow!("Could not lock lifecycle policy"))?
+            .clone();
+        let now = SystemTime::now();
+
+        if let Some(ttl) = policy.idle_ttl {
+            for id in idle_expired_ids(&store.activity()?, ttl, now) {
+                if let Some(session) = store.remove(id)? {
+                    info!("Reaping idle session {}", id);
+                    session.teardown();
+                }
+            }
         }
+
+        if let Some(cap) = policy.max_sessions {
+            for id in lru_evict_ids(&store.activity()?, cap) {
+                if let Some(session) = store.remove(id)? {
+                    info!("Evicting session {} to honour max-session cap", id);
+                    session.teardown();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the lifecycle sweep on demand, e.g. from a caller's background reaper
+    /// thread. Closes idle sessions and enforces the capacity cap.
+    pub fn reap_expired() -> Result<()> {
+        let store = session_store()?;
+        Self::reap(&store)
+    }
+}
+
+#[cfg(test)]
+mod lifecycle_tests {
+    use super::*;
+
+    #[test]
+    fn idle_expiry_selects_only_sessions_past_ttl() {
+        let now = SystemTime::now();
+        let activity = vec![
+            (1, now - Duration::from_secs(5)),
+            (2, now - Duration::from_secs(120)),
+            (3, now - Duration::from_secs(61)),
+        ];
+        let mut expired = idle_expired_ids(&activity, Duration::from_secs(60), now);
+        expired.sort();
+        assert_eq!(expired, vec![2, 3]);
+    }
+
+    #[test]
+    fn idle_expiry_with_no_sessions_past_ttl_is_empty() {
+        let now = SystemTime::now();
+        let activity = vec![(1, now - Duration::from_secs(5))];
+        assert!(idle_expired_ids(&activity, Duration::from_secs(60), now).is_empty());
+    }
+
+    #[test]
+    fn idle_expiry_survives_non_monotonic_clock_skew() {
+        // last_activity lands fractionally after `now`, which duration_since
+        // reports as an error; this must not be treated as idle.
+        let now = SystemTime::now();
+        let activity = vec![(1, now + Duration::from_millis(1))];
+        assert!(idle_expired_ids(&activity, Duration::from_secs(60), now).is_empty());
+    }
+
+    #[test]
+    fn lru_eviction_trims_down_to_cap_oldest_first() {
+        let now = SystemTime::now();
+        let activity = vec![
+            (1, now - Duration::from_secs(10)),
+            (2, now - Duration::from_secs(30)),
+            (3, now - Duration::from_secs(5)),
+            (4, now - Duration::from_secs(20)),
+        ];
+        let mut evicted = lru_evict_ids(&activity, 2);
+        evicted.sort();
+        assert_eq!(evicted, vec![2, 4]);
+    }
+
+    #[test]
+    fn lru_eviction_under_cap_is_a_noop() {
+        let now = SystemTime::now();
+        let activity = vec![(1, now), (2, now)];
+        assert!(lru_evict_ids(&activity, 5).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod session_registry_tests {
+    use super::*;
+
+    #[test]
+    fn register_is_idempotent() {
+        let mut registry = SessionRegistry::new();
+        registry.register(1);
+        registry.register(1);
+        registry.register(2);
+        assert_eq!(registry.sessions(), &[1, 2]);
+    }
+
+    #[test]
+    fn deregister_removes_only_the_given_session() {
+        let mut registry = SessionRegistry::new();
+        registry.register(1);
+        registry.register(2);
+        registry.deregister(1);
+        assert_eq!(registry.sessions(), &[2]);
+    }
+
+    #[test]
+    fn deregister_unknown_session_is_a_noop() {
+        let mut registry = SessionRegistry::new();
+        registry.register(1);
+        registry.deregister(42);
+        assert_eq!(registry.sessions(), &[1]);
     }
 }